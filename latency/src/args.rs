@@ -0,0 +1,39 @@
+use std::net::SocketAddr;
+
+use clap::{Parser, ValueEnum};
+
+use crate::protocol;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Protocol {
+    Udp,
+    Tcp,
+    Quic,
+    Unix,
+}
+
+impl From<Protocol> for protocol::TestType {
+    fn from(p: Protocol) -> Self {
+        match p {
+            Protocol::Udp => protocol::TestType::Udp,
+            Protocol::Tcp => protocol::TestType::Tcp,
+            Protocol::Quic => protocol::TestType::Quic,
+            Protocol::Unix => protocol::TestType::Unix,
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Args {
+    /// Control-plane address to listen on.
+    #[arg(long)]
+    pub listen: SocketAddr,
+
+    /// Default data-plane transport for sessions that don't negotiate one.
+    #[arg(long, value_enum, default_value_t = Protocol::Udp)]
+    pub protocol: Protocol,
+
+    /// Default number of parallel probe channels per session.
+    #[arg(long, default_value_t = 1)]
+    pub channels: u64,
+}
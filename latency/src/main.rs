@@ -8,13 +8,22 @@ use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 mod args;
 mod protocol;
+mod quic;
+mod stats;
 mod tcp;
 mod traits;
+mod unix;
 mod utils;
 #[tokio::main(flavor = "current_thread")]
-async fn main() {
+async fn main() -> Result<()> {
+    let args = <args::Args as clap::Parser>::parse();
+    let mut server =
+        CtrlServer::new(args.listen, args.channels, args.protocol.into());
+    server.run().await
 }
 
+const DATA_PLANE_BASE_PORT: u16 = 45000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct LatencyMsg {
     id: u64,
@@ -88,14 +97,7 @@ impl<Kind> Latency for UdpLatency<Kind> {
 
     async fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
         let (len, recv_addr) = self.inner.recv_from(buf).await?;
-        if self.peer.is_some() {
-            if recv_addr != self.peer.unwrap() {
-                return Err(anyhow::anyhow!("recv from wrong addr"));
-            }
-        } else {
-            self.peer = Some(recv_addr);
-        }
-
+        utils::track_peer(&mut self.peer, recv_addr)?;
         Ok(len)
     }
 }
@@ -120,15 +122,23 @@ impl ClientCtx {
 struct CtrlServer {
     quinn: common::QuicServer,
     clients: Vec<ClientCtx>,
+    default_channels: u64,
+    default_protocol: protocol::TestType,
 }
 
 impl CtrlServer {
-    fn new(lst_addr: SocketAddr) -> Self {
+    fn new(
+        lst_addr: SocketAddr,
+        default_channels: u64,
+        default_protocol: protocol::TestType,
+    ) -> Self {
         let quinn =
             common::QuicServer::new((lst_addr.ip(), lst_addr.port())).unwrap();
         Self {
             quinn,
             clients: Vec::new(),
+            default_channels,
+            default_protocol,
         }
     }
 
@@ -138,7 +148,7 @@ impl CtrlServer {
                 Some(connecting) = self.quinn.server.accept() => {
                     let conn = connecting.await?;
                     let (tx, rx) = conn.open_bi().await?;
-                    self.handle_client(tx, rx).await?;
+                    self.handle_client(tx, rx, conn).await?;
                 }
             _ = tokio::signal::ctrl_c() => {
                     for client in self.clients.iter_mut() {
@@ -156,11 +166,27 @@ impl CtrlServer {
         &mut self,
         tx: quinn::SendStream,
         rx: quinn::RecvStream,
+        conn: quinn::Connection,
     ) -> Result<()> {
         let (stop, stop_rx) = tokio::sync::oneshot::channel();
-        let mut client = Client::new(tx, rx, stop_rx, 1000, 1000, 1000);
+        let mut client = Client::new(
+            tx,
+            rx,
+            conn,
+            stop_rx,
+            1000,
+            500,
+            1000,
+            1000,
+            self.default_channels,
+            self.default_protocol,
+        );
         let fut = tokio::spawn(async move {
-            client.run().await;
+            let report = client.run().await;
+            for (id, summary) in &report.per_channel {
+                println!("channel {} summary: {:?}", id, summary);
+            }
+            println!("aggregate summary: {:?}", report.aggregate);
         });
         self.clients.push(ClientCtx::new(stop, fut));
         Ok(())
@@ -175,74 +201,235 @@ mod side {
 struct Client {
     ctx: Vec<ConnCtx>,
     timeout: u64,
+    drain_timeout: u64,
     interval: u64,
     packet_size: u64,
+    /// Number of parallel probe flows to fan out, each with its own `id`
+    /// and sequence space.
+    channels: u64,
+    /// Data-plane transport this session negotiates via the handshake.
+    protocol: protocol::TestType,
+    conn: quinn::Connection,
     tx_ctrl: quinn::SendStream,
     rx_ctrl: quinn::RecvStream,
     stop: tokio::sync::oneshot::Receiver<()>,
     id: u64,
+    stats: std::collections::HashMap<u64, stats::FlowStats>,
+    msg_tx: tokio::sync::mpsc::UnboundedSender<(u64, LatencyMsg, u64)>,
+    msg_rx: tokio::sync::mpsc::UnboundedReceiver<(u64, LatencyMsg, u64)>,
 }
 
 impl Client {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         tx_ctrl: quinn::SendStream,
         rx_ctrl: quinn::RecvStream,
+        conn: quinn::Connection,
         stop: tokio::sync::oneshot::Receiver<()>,
         timeout: u64,
+        drain_timeout: u64,
         interval: u64,
         packet_size: u64,
+        channels: u64,
+        protocol: protocol::TestType,
     ) -> Self {
+        let (msg_tx, msg_rx) = tokio::sync::mpsc::unbounded_channel();
         Self {
             ctx: Vec::new(),
             timeout,
+            drain_timeout,
             interval,
             packet_size,
+            channels,
+            protocol,
+            conn,
             tx_ctrl,
             rx_ctrl,
             stop,
             id: 0,
+            stats: std::collections::HashMap::new(),
+            msg_tx,
+            msg_rx,
         }
     }
 
-    async fn run(&mut self) {
+    /// Spin up `self.channels` parallel flows sharing the same interval
+    /// timer, each with its own `id` and independent seq counter, via
+    /// `make_socket` to build the transport for a given channel id.
+    fn spawn_channels<F, T>(&mut self, make_socket: F)
+    where
+        F: Fn(u64) -> T,
+        T: Latency + Send + 'static,
+    {
+        for id in 0..self.channels {
+            self.spawn_conn(id, make_socket(id));
+        }
+    }
+
+    /// Pair a new [`Latency`] socket with a [`ConnRunner`], giving the flow
+    /// its own `id` for sequence tracking, and register it with this client.
+    fn spawn_conn<T: Latency + Send + 'static>(&mut self, id: u64, socket: T) {
+        let (p1, p2) = tokio::io::duplex(u16::MAX as usize);
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+        let mut runner = ConnRunner::new(socket, p1, stop_rx);
+        tokio::spawn(async move {
+            runner.run().await;
+        });
+
+        let (mut reader, writer) = tokio::io::split(p2);
+        let msg_tx = self.msg_tx.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; u16::MAX as usize];
+            loop {
+                let len = match reader.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(len) => len,
+                };
+                let recv_ts = stats::now_micros();
+                if let Ok(msg) = bincode::deserialize::<LatencyMsg>(&buf[..len])
+                {
+                    if msg_tx.send((id, msg, recv_ts)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        self.ctx.push(ConnCtx::new(writer, stop_tx, id));
+    }
+
+    async fn run(&mut self) -> stats::ChannelReport {
         let mut snd_timer =
             tokio::time::interval(Duration::from_millis(self.interval));
         let packet_size = self.packet_size;
         assert!(packet_size <= u16::MAX as u64);
-        let mut sndbuf = [0u8; u16::MAX as usize];
         let mut recvbuf = [0u8; u16::MAX as usize];
 
         loop {
             tokio::select! {
                 _ = snd_timer.tick() => {
-                    let sndbuf = sndbuf[..packet_size as usize].to_vec();
                     for ctx in self.ctx.iter_mut() {
-                        ctx.bidi.write_all(&sndbuf).await.unwrap();
+                        let msg = LatencyMsg {
+                            id: ctx.id,
+                            seq: ctx.seq,
+                            timestamp: stats::now_micros(),
+                        };
+                        ctx.seq += 1;
+                        let mut buf = bincode::serialize(&msg).unwrap();
+                        buf.resize(packet_size as usize, 0);
+                        ctx.bidi.write_all(&buf).await.unwrap();
                     }
                 }
+                Some((id, msg, recv_ts)) = self.msg_rx.recv() => {
+                    self.stats
+                        .entry(id)
+                        .or_insert_with(stats::FlowStats::new)
+                        .record(msg.seq, msg.timestamp, recv_ts);
+                }
                 Ok(Some(len)) = self.rx_ctrl.read(&mut recvbuf) => {
                     let recvbuf = recvbuf[..len].to_vec();
                     self.handle_ctrl_msg(&recvbuf).await;
                 }
                 _ = tokio::time::sleep(Duration::from_millis(self.timeout)) => {
-                    for ctx in self.ctx.iter_mut() {
-                        ctx.stop.take().unwrap().send(()).unwrap();
-                    }
                     break;
                 }
                 _ = &mut self.stop => {
-                    for ctx in self.ctx.iter_mut() {
-                        ctx.stop.take().unwrap().send(()).unwrap();
-                    }
                     break;
                 }
 
             }
         }
+
+        // Stop sending new probes but keep each ConnRunner's receive side
+        // alive so echoes already in flight can still be matched to their
+        // seq before we tear down and report.
+        self.drain().await;
+
+        for ctx in self.ctx.iter_mut() {
+            ctx.stop.take().unwrap().send(()).unwrap();
+        }
+
+        let per_channel = self
+            .ctx
+            .iter()
+            .map(|ctx| {
+                let outstanding = if ctx.seq == 0 { None } else { Some(ctx.seq - 1) };
+                let summary = self
+                    .stats
+                    .get(&ctx.id)
+                    .map(|s| s.summary(outstanding))
+                    .unwrap_or_else(|| stats::FlowStats::new().summary(outstanding));
+                (ctx.id, summary)
+            })
+            .collect();
+
+        stats::ChannelReport::new(per_channel)
+    }
+
+    /// Wait for every flow's last sent probe to be accounted for (echoed or
+    /// implicitly lost behind a later seq), up to `drain_timeout`.
+    async fn drain(&mut self) {
+        drain_probes(
+            &self.ctx,
+            &mut self.stats,
+            &mut self.msg_rx,
+            self.drain_timeout,
+        )
+        .await;
+    }
+
+    fn all_probes_accounted_for(&self) -> bool {
+        probes_accounted_for(&self.ctx, &self.stats)
     }
 
     async fn handle_ctrl_msg(&mut self, msg: &[u8]) {
-        todo!()
+        let Ok(protocol::ClientMessage::Handshake(handshake)) =
+            bincode::deserialize(msg)
+        else {
+            return;
+        };
+
+        if handshake.channels != 0 {
+            self.channels = handshake.channels;
+        }
+
+        match protocol::TestType::from_u64(handshake.protocol)
+            .or(Some(self.protocol))
+        {
+            Some(protocol::TestType::Quic) => {
+                for id in 0..self.channels {
+                    let socket =
+                        quic::QuicLatency::<socket_kind::Server>::new(self.conn.clone());
+                    self.spawn_conn(id, socket);
+                }
+            }
+            Some(protocol::TestType::Tcp) => {
+                for id in 0..self.channels {
+                    let socket = tcp::TcpLatency::<socket_kind::Server>::new(&format!(
+                        "0.0.0.0:{}",
+                        DATA_PLANE_BASE_PORT + id as u16
+                    ))
+                    .await;
+                    self.spawn_conn(id, socket);
+                }
+            }
+            Some(protocol::TestType::Unix) => {
+                for id in 0..self.channels {
+                    let path = std::env::temp_dir()
+                        .join(format!("niceperf-latency-server-{id}.sock"));
+                    let socket = unix::UnixLatency::<socket_kind::Server>::new(path);
+                    self.spawn_conn(id, socket);
+                }
+            }
+            _ => {
+                self.spawn_channels(|id| {
+                    UdpLatency::<socket_kind::Server>::new(&format!(
+                        "0.0.0.0:{}",
+                        DATA_PLANE_BASE_PORT + id as u16
+                    ))
+                });
+            }
+        }
     }
 
     async fn handshake(
@@ -254,7 +441,8 @@ impl Client {
         let handshake =
             protocol::ClientMessage::Handshake(protocol::ClientHandshake {
                 id: self.id as u64,
-                protocol: protocol::TestType::Udp as u64,
+                protocol: self.protocol as u64,
+                channels: self.channels,
             });
         let mut handshake_timer = tokio::time::interval(interval);
 
@@ -278,22 +466,74 @@ impl Client {
     }
 }
 
+/// `true` once every flow's last sent probe has been echoed or superseded
+/// by a later seq. A flow that never sent anything (`seq == 0`) is
+/// trivially accounted for.
+fn probes_accounted_for(
+    ctx: &[ConnCtx],
+    stats: &std::collections::HashMap<u64, stats::FlowStats>,
+) -> bool {
+    ctx.iter().all(|ctx| {
+        if ctx.seq == 0 {
+            return true;
+        }
+        let last_sent = ctx.seq - 1;
+        stats
+            .get(&ctx.id)
+            .and_then(|s| s.highest_seq())
+            .is_some_and(|highest| highest >= last_sent)
+    })
+}
+
+/// Keep recording echoes arriving on `msg_rx` until every flow in `ctx` is
+/// accounted for, or `drain_timeout` elapses first.
+async fn drain_probes(
+    ctx: &[ConnCtx],
+    stats: &mut std::collections::HashMap<u64, stats::FlowStats>,
+    msg_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(u64, LatencyMsg, u64)>,
+    drain_timeout: u64,
+) {
+    let deadline = tokio::time::sleep(Duration::from_millis(drain_timeout));
+    tokio::pin!(deadline);
+
+    while !probes_accounted_for(ctx, stats) {
+        tokio::select! {
+            Some((id, msg, recv_ts)) = msg_rx.recv() => {
+                stats
+                    .entry(id)
+                    .or_insert_with(stats::FlowStats::new)
+                    .record(msg.seq, msg.timestamp, recv_ts);
+            }
+            _ = &mut deadline => {
+                break;
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct ConnCtx<State = conn_state::Disconnected> {
-    bidi: tokio::io::DuplexStream,
+    bidi: tokio::io::WriteHalf<tokio::io::DuplexStream>,
     stop: Option<tokio::sync::oneshot::Sender<()>>,
     state: std::marker::PhantomData<State>,
+    /// Distinguishes this flow's `LatencyMsg`s from those of sibling flows.
+    id: u64,
+    /// Next sequence number to stamp on an outgoing probe.
+    seq: u64,
 }
 
 impl ConnCtx<conn_state::Disconnected> {
     fn new(
-        bidi: tokio::io::DuplexStream,
+        bidi: tokio::io::WriteHalf<tokio::io::DuplexStream>,
         stop: tokio::sync::oneshot::Sender<()>,
+        id: u64,
     ) -> Self {
         Self {
             bidi,
             stop: Some(stop),
             state: std::marker::PhantomData,
+            id,
+            seq: 0,
         }
     }
 }
@@ -336,42 +576,89 @@ impl<T: Latency> ConnRunner<T> {
 }
 
 mod test {
+    use super::*;
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-    async fn test_server() -> ConnCtx {
-        let (p1, mut p2) = tokio::io::duplex(1024);
+    async fn test_server(
+    ) -> (ConnCtx, tokio::io::ReadHalf<tokio::io::DuplexStream>) {
+        let (p1, p2) = tokio::io::duplex(1024);
         let (tx, rx) = tokio::sync::oneshot::channel();
         let socket = UdpLatency::<socket_kind::Server>::new("127.0.0.1:12345");
         let mut runner = ConnRunner::new(socket, p1, rx);
-        let ctx = ConnCtx::new(p2, tx);
+        let (reader, writer) = tokio::io::split(p2);
+        let ctx = ConnCtx::new(writer, tx, 0);
         tokio::spawn(async move {
             runner.run().await;
         });
-        ctx
+        (ctx, reader)
     }
-    async fn test_client() -> ConnCtx {
-        let (p1, mut p2) = tokio::io::duplex(1024);
+    async fn test_client(
+    ) -> (ConnCtx, tokio::io::ReadHalf<tokio::io::DuplexStream>) {
+        let (p1, p2) = tokio::io::duplex(1024);
         let (tx, rx) = tokio::sync::oneshot::channel();
         let socket = UdpLatency::<socket_kind::Client>::new("127.0.0.1:12345");
         let mut runner = ConnRunner::new(socket, p1, rx);
-        let ctx = ConnCtx::new(p2, tx);
+        let (reader, writer) = tokio::io::split(p2);
+        let ctx = ConnCtx::new(writer, tx, 0);
         tokio::spawn(async move {
             runner.run().await;
         });
+        (ctx, reader)
+    }
+
+    fn fake_ctx(id: u64, seq: u64) -> ConnCtx {
+        let (_p1, p2) = tokio::io::duplex(1024);
+        let (stop, _stop_rx) = tokio::sync::oneshot::channel();
+        let (_reader, writer) = tokio::io::split(p2);
+        let mut ctx = ConnCtx::new(writer, stop, id);
+        ctx.seq = seq;
         ctx
     }
 
-    use super::*;
+    #[tokio::test(flavor = "current_thread")]
+    async fn drain_captures_an_echo_that_arrives_before_the_deadline() {
+        let ctx = vec![fake_ctx(0, 1)];
+        let mut stats = std::collections::HashMap::new();
+        let (msg_tx, mut msg_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        msg_tx
+            .send((
+                0,
+                LatencyMsg {
+                    id: 0,
+                    seq: 0,
+                    timestamp: 0,
+                },
+                0,
+            ))
+            .unwrap();
+
+        drain_probes(&ctx, &mut stats, &mut msg_rx, 200).await;
+
+        assert!(probes_accounted_for(&ctx, &stats));
+        assert_eq!(stats.get(&0).unwrap().highest_seq(), Some(0));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn drain_gives_up_and_leaves_the_probe_unaccounted_for_past_the_deadline(
+    ) {
+        let ctx = vec![fake_ctx(0, 1)];
+        let mut stats = std::collections::HashMap::new();
+        let (_msg_tx, mut msg_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        drain_probes(&ctx, &mut stats, &mut msg_rx, 20).await;
+
+        assert!(!probes_accounted_for(&ctx, &stats));
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn test() {
-        let mut client_ctx = test_client().await;
-        let mut server_ctx = test_server().await;
+        let (mut client_ctx, _client_reader) = test_client().await;
+        let (mut server_ctx, mut server_reader) = test_server().await;
 
-        let mut sndbuf = [0u8; u16::MAX as usize];
         let mut recvbuf = [0u8; u16::MAX as usize];
 
         let mut snd_timer = tokio::time::interval(Duration::from_millis(1000));
-        let timeout = Duration::from_millis(10000);
 
         loop {
             tokio::select! {
@@ -379,7 +666,7 @@ mod test {
                     let sndbuf = "hello".as_bytes().to_vec();
                     client_ctx.bidi.write_all(&sndbuf).await.unwrap();
                 }
-                Ok(len) = server_ctx.bidi.read(&mut recvbuf) => {
+                Ok(len) = server_reader.read(&mut recvbuf) => {
                     let recvbuf = recvbuf[..len].to_vec();
                     server_ctx.bidi.write_all(&recvbuf).await.unwrap();
                     println!("recv: {:?}", recvbuf);
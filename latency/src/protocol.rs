@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestType {
+    Udp,
+    Tcp,
+    Quic,
+    Unix,
+}
+
+impl TestType {
+    pub fn from_u64(v: u64) -> Option<Self> {
+        match v {
+            0 => Some(TestType::Udp),
+            1 => Some(TestType::Tcp),
+            2 => Some(TestType::Quic),
+            3 => Some(TestType::Unix),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientHandshake {
+    pub id: u64,
+    pub protocol: u64,
+    pub channels: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Handshake(ClientHandshake),
+}
@@ -0,0 +1,47 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+
+use crate::{socket_kind, Latency};
+
+pub struct QuicLatency<Kind> {
+    conn: quinn::Connection,
+    kind: std::marker::PhantomData<Kind>,
+}
+
+impl QuicLatency<socket_kind::Server> {
+    pub fn new(conn: quinn::Connection) -> Self {
+        Self {
+            conn,
+            kind: std::marker::PhantomData,
+        }
+    }
+}
+
+impl QuicLatency<socket_kind::Client> {
+    pub fn new(conn: quinn::Connection) -> Self {
+        Self {
+            conn,
+            kind: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<Kind> Latency for QuicLatency<Kind> {
+    async fn send(&mut self, buf: &[u8]) -> Result<usize> {
+        self.conn.send_datagram(Bytes::copy_from_slice(buf))?;
+        Ok(buf.len())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let datagram = self.conn.read_datagram().await?;
+        if datagram.len() > buf.len() {
+            return Err(anyhow!(
+                "datagram of {} bytes does not fit the supplied buffer of {}",
+                datagram.len(),
+                buf.len()
+            ));
+        }
+        buf[..datagram.len()].copy_from_slice(&datagram);
+        Ok(datagram.len())
+    }
+}
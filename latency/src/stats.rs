@@ -0,0 +1,337 @@
+//! Receiver-side statistics for a single probe flow (one `id`).
+//!
+//! Consumes echoed `LatencyMsg`s as they arrive, tracking loss/reorder via
+//! the sequence number and RTT/jitter via the embedded timestamp.
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of recent RTT samples kept for percentile estimation.
+const RESERVOIR_CAP: usize = 4096;
+
+/// Number of recently-accepted seqs kept to catch duplicates of a seq that
+/// has already been superseded by a later one.
+const RECENT_SEQS_CAP: usize = 64;
+
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as u64
+}
+
+/// Per-flow receiver statistics, keyed implicitly by `LatencyMsg::id`.
+#[derive(Debug)]
+pub struct FlowStats {
+    highest_seq: Option<u64>,
+    recent_seqs: VecDeque<u64>,
+    received: u64,
+    lost: u64,
+    reordered: u64,
+    duplicates: u64,
+
+    rtt_min: u64,
+    rtt_max: u64,
+    rtt_sum: u128,
+    rtt_reservoir: VecDeque<u64>,
+
+    jitter: f64,
+    last_send_ts: Option<u64>,
+    last_recv_ts: Option<u64>,
+}
+
+impl Default for FlowStats {
+    fn default() -> Self {
+        Self {
+            highest_seq: None,
+            recent_seqs: VecDeque::with_capacity(RECENT_SEQS_CAP),
+            received: 0,
+            lost: 0,
+            reordered: 0,
+            duplicates: 0,
+            rtt_min: u64::MAX,
+            rtt_max: 0,
+            rtt_sum: 0,
+            rtt_reservoir: VecDeque::with_capacity(RESERVOIR_CAP),
+            jitter: 0.0,
+            last_send_ts: None,
+            last_recv_ts: None,
+        }
+    }
+}
+
+impl FlowStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Highest seq observed so far for this flow, if any.
+    pub fn highest_seq(&self) -> Option<u64> {
+        self.highest_seq
+    }
+
+    /// Track `seq` in the bounded recently-accepted window so a later
+    /// redelivery of it can be recognized as a duplicate rather than a
+    /// reorder.
+    fn remember_seq(&mut self, seq: u64) {
+        if self.recent_seqs.len() == RECENT_SEQS_CAP {
+            self.recent_seqs.pop_front();
+        }
+        self.recent_seqs.push_back(seq);
+    }
+
+    /// Record an echoed message. `send_ts`/`recv_ts` are both in the sender's
+    /// clock domain (the timestamp carried in the message and the local
+    /// arrival time converted to the same epoch) so that `rtt` is simply
+    /// `recv_ts - send_ts`.
+    pub fn record(&mut self, seq: u64, send_ts: u64, recv_ts: u64) {
+        self.received += 1;
+
+        match self.highest_seq {
+            None => {
+                self.highest_seq = Some(seq);
+                self.remember_seq(seq);
+            }
+            Some(highest) => {
+                if seq > highest {
+                    self.lost += seq - highest - 1;
+                    self.highest_seq = Some(seq);
+                    self.remember_seq(seq);
+                } else if self.recent_seqs.contains(&seq) {
+                    self.duplicates += 1;
+                } else {
+                    self.reordered += 1;
+                    self.remember_seq(seq);
+                }
+            }
+        }
+
+        let rtt = recv_ts.saturating_sub(send_ts);
+        self.rtt_min = self.rtt_min.min(rtt);
+        self.rtt_max = self.rtt_max.max(rtt);
+        self.rtt_sum += rtt as u128;
+        if self.rtt_reservoir.len() == RESERVOIR_CAP {
+            self.rtt_reservoir.pop_front();
+        }
+        self.rtt_reservoir.push_back(rtt);
+
+        // RFC 3550 interarrival jitter: J += (|D| - J) / 16, where
+        // D = (recv_i - recv_{i-1}) - (send_i - send_{i-1}).
+        if let (Some(last_send), Some(last_recv)) =
+            (self.last_send_ts, self.last_recv_ts)
+        {
+            let d = (recv_ts as i64 - last_recv as i64)
+                - (send_ts as i64 - last_send as i64);
+            self.jitter += (d.unsigned_abs() as f64 - self.jitter) / 16.0;
+        }
+        self.last_send_ts = Some(send_ts);
+        self.last_recv_ts = Some(recv_ts);
+    }
+
+    /// Finalize the flow. `lost` is the seqs skipped whenever the highest seq
+    /// advanced (a skipped probe that arrives later stays counted as lost
+    /// and also counts as reordered), plus every probe after the highest seq
+    /// seen up to `outstanding`, the seq of the last probe actually sent. If
+    /// nothing was echoed, every probe up to `outstanding` is lost.
+    pub fn summary(&self, outstanding: Option<u64>) -> FlowSummary {
+        let mut lost = self.lost;
+        if let Some(outstanding) = outstanding {
+            match self.highest_seq {
+                Some(highest) if outstanding > highest => {
+                    lost += outstanding - highest;
+                }
+                Some(_) => {}
+                None => lost += outstanding + 1,
+            }
+        }
+
+        let mut sorted: Vec<u64> = self.rtt_reservoir.iter().copied().collect();
+        sorted.sort_unstable();
+
+        FlowSummary {
+            received: self.received,
+            lost,
+            reordered: self.reordered,
+            duplicates: self.duplicates,
+            rtt_min: if self.received == 0 { 0 } else { self.rtt_min },
+            rtt_max: self.rtt_max,
+            rtt_avg: if self.received == 0 {
+                0.0
+            } else {
+                self.rtt_sum as f64 / self.received as f64
+            },
+            rtt_p50: percentile(&sorted, 0.50),
+            rtt_p90: percentile(&sorted, 0.90),
+            rtt_p99: percentile(&sorted, 0.99),
+            jitter: self.jitter,
+        }
+    }
+}
+
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let idx = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[idx]
+}
+
+/// A snapshot of `FlowStats`, returned when a flow stops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlowSummary {
+    pub received: u64,
+    pub lost: u64,
+    pub reordered: u64,
+    pub duplicates: u64,
+    pub rtt_min: u64,
+    pub rtt_max: u64,
+    pub rtt_avg: f64,
+    pub rtt_p50: u64,
+    pub rtt_p90: u64,
+    pub rtt_p99: u64,
+    pub jitter: f64,
+}
+
+impl FlowSummary {
+    /// Merge multiple channels' summaries into one, weighting averaged
+    /// fields (RTT stats, jitter) by each channel's received count so a
+    /// quiet channel doesn't skew the aggregate as much as a busy one.
+    pub fn aggregate(summaries: &[FlowSummary]) -> FlowSummary {
+        let received: u64 = summaries.iter().map(|s| s.received).sum();
+        let weighted = |f: fn(&FlowSummary) -> f64| -> f64 {
+            if received == 0 {
+                return 0.0;
+            }
+            summaries
+                .iter()
+                .map(|s| f(s) * s.received as f64)
+                .sum::<f64>()
+                / received as f64
+        };
+
+        FlowSummary {
+            received,
+            lost: summaries.iter().map(|s| s.lost).sum(),
+            reordered: summaries.iter().map(|s| s.reordered).sum(),
+            duplicates: summaries.iter().map(|s| s.duplicates).sum(),
+            rtt_min: summaries
+                .iter()
+                .filter(|s| s.received > 0)
+                .map(|s| s.rtt_min)
+                .min()
+                .unwrap_or(0),
+            rtt_max: summaries.iter().map(|s| s.rtt_max).max().unwrap_or(0),
+            rtt_avg: weighted(|s| s.rtt_avg),
+            rtt_p50: weighted(|s| s.rtt_p50 as f64) as u64,
+            rtt_p90: weighted(|s| s.rtt_p90 as f64) as u64,
+            rtt_p99: weighted(|s| s.rtt_p99 as f64) as u64,
+            jitter: weighted(|s| s.jitter),
+        }
+    }
+}
+
+/// Per-channel summaries for a multi-flow run, plus the merged total.
+#[derive(Debug, Clone)]
+pub struct ChannelReport {
+    pub per_channel: Vec<(u64, FlowSummary)>,
+    pub aggregate: FlowSummary,
+}
+
+impl ChannelReport {
+    pub fn new(per_channel: Vec<(u64, FlowSummary)>) -> Self {
+        let summaries: Vec<FlowSummary> =
+            per_channel.iter().map(|(_, s)| *s).collect();
+        Self {
+            aggregate: FlowSummary::aggregate(&summaries),
+            per_channel,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tracks_loss_and_reorder() {
+        let mut stats = FlowStats::new();
+        stats.record(0, 0, 10);
+        stats.record(1, 10, 25);
+        stats.record(3, 20, 35); // seq 2 lost
+        stats.record(2, 30, 45); // late arrival, reordered
+
+        let summary = stats.summary(Some(3));
+        assert_eq!(summary.received, 4);
+        assert_eq!(summary.lost, 1);
+        assert_eq!(summary.reordered, 1);
+        assert_eq!(summary.duplicates, 0);
+    }
+
+    #[test]
+    fn redelivery_of_a_superseded_seq_is_a_duplicate() {
+        let mut stats = FlowStats::new();
+        stats.record(0, 0, 10);
+        stats.record(1, 10, 20);
+        stats.record(2, 20, 30);
+        stats.record(0, 30, 40); // seq 0 redelivered after 1, 2 advanced highest
+
+        let summary = stats.summary(Some(2));
+        assert_eq!(summary.duplicates, 1);
+        assert_eq!(summary.reordered, 0);
+    }
+
+    #[test]
+    fn counts_outstanding_as_lost_on_drain() {
+        let mut stats = FlowStats::new();
+        stats.record(0, 0, 10);
+        let summary = stats.summary(Some(2));
+        assert_eq!(summary.lost, 2);
+    }
+
+    #[test]
+    fn aggregate_weights_rtt_by_received_count() {
+        let busy = FlowSummary {
+            received: 3,
+            lost: 1,
+            reordered: 0,
+            duplicates: 0,
+            rtt_min: 10,
+            rtt_max: 30,
+            rtt_avg: 20.0,
+            rtt_p50: 20,
+            rtt_p90: 30,
+            rtt_p99: 30,
+            jitter: 2.0,
+        };
+        let quiet = FlowSummary {
+            received: 1,
+            lost: 0,
+            reordered: 1,
+            duplicates: 2,
+            rtt_min: 100,
+            rtt_max: 100,
+            rtt_avg: 100.0,
+            rtt_p50: 100,
+            rtt_p90: 100,
+            rtt_p99: 100,
+            jitter: 10.0,
+        };
+
+        let aggregate = FlowSummary::aggregate(&[busy, quiet]);
+        assert_eq!(aggregate.received, 4);
+        assert_eq!(aggregate.lost, 1);
+        assert_eq!(aggregate.reordered, 1);
+        assert_eq!(aggregate.duplicates, 2);
+        assert_eq!(aggregate.rtt_min, 10);
+        assert_eq!(aggregate.rtt_max, 100);
+        // (20.0 * 3 + 100.0 * 1) / 4
+        assert_eq!(aggregate.rtt_avg, 40.0);
+    }
+
+    #[test]
+    fn aggregate_of_no_channels_is_zeroed() {
+        let aggregate = FlowSummary::aggregate(&[]);
+        assert_eq!(aggregate.received, 0);
+        assert_eq!(aggregate.rtt_avg, 0.0);
+    }
+}
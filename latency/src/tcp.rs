@@ -0,0 +1,94 @@
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::{socket_kind, Latency};
+
+const MAX_FRAME_LEN: usize = u16::MAX as usize;
+
+pub struct TcpLatency<Kind> {
+    inner: TcpStream,
+    kind: std::marker::PhantomData<Kind>,
+}
+
+impl TcpLatency<socket_kind::Server> {
+    pub async fn new(local: &str) -> Self {
+        let listener = TcpListener::bind(local).await.unwrap();
+        let (inner, _peer) = listener.accept().await.unwrap();
+
+        Self {
+            inner,
+            kind: std::marker::PhantomData,
+        }
+    }
+}
+
+impl TcpLatency<socket_kind::Client> {
+    pub async fn new(remote: &str) -> Self {
+        let inner = TcpStream::connect(remote).await.unwrap();
+
+        Self {
+            inner,
+            kind: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<Kind> Latency for TcpLatency<Kind> {
+    async fn send(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.len() > MAX_FRAME_LEN {
+            return Err(anyhow!(
+                "message of {} bytes does not fit a 2-byte length prefix",
+                buf.len()
+            ));
+        }
+
+        self.inner
+            .write_all(&(buf.len() as u16).to_be_bytes())
+            .await?;
+        self.inner.write_all(buf).await?;
+        Ok(buf.len())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut len_buf = [0u8; 2];
+        self.inner.read_exact(&mut len_buf).await?;
+        let len = u16::from_be_bytes(len_buf) as usize;
+
+        if len > buf.len() {
+            return Err(anyhow!(
+                "frame of {len} bytes does not fit the supplied buffer of {}",
+                buf.len()
+            ));
+        }
+
+        self.inner.read_exact(&mut buf[..len]).await?;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn round_trips_a_frame() {
+        let addr = "127.0.0.1:34566";
+        let (mut server, mut client) = tokio::join!(
+            TcpLatency::<socket_kind::Server>::new(addr),
+            async {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                TcpLatency::<socket_kind::Client>::new(addr).await
+            }
+        );
+
+        client.send(b"hello").await.unwrap();
+        let mut buf = [0u8; 1024];
+        let len = server.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"hello");
+
+        server.send(b"world").await.unwrap();
+        let len = client.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"world");
+    }
+}
@@ -0,0 +1,90 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use tokio::net::UnixDatagram;
+
+use crate::{socket_kind, utils, Latency};
+
+pub struct UnixLatency<Kind> {
+    inner: UnixDatagram,
+    peer: Option<PathBuf>,
+    kind: std::marker::PhantomData<Kind>,
+}
+
+impl UnixLatency<socket_kind::Server> {
+    pub fn new(local: impl AsRef<Path>) -> Self {
+        let local = local.as_ref();
+        let _ = std::fs::remove_file(local);
+        let inner = UnixDatagram::bind(local).unwrap();
+
+        Self {
+            inner,
+            peer: None,
+            kind: std::marker::PhantomData,
+        }
+    }
+}
+
+impl UnixLatency<socket_kind::Client> {
+    // channel_id keeps sibling channels' ephemeral bind paths from colliding.
+    pub fn new(remote: impl AsRef<Path>, channel_id: u64) -> Self {
+        let local = std::env::temp_dir().join(format!(
+            "niceperf-latency-{}-{}.sock",
+            std::process::id(),
+            channel_id
+        ));
+        let _ = std::fs::remove_file(&local);
+        let inner = UnixDatagram::bind(&local).unwrap();
+
+        Self {
+            inner,
+            peer: Some(remote.as_ref().to_path_buf()),
+            kind: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<Kind> Latency for UnixLatency<Kind> {
+    async fn send(&mut self, buf: &[u8]) -> Result<usize> {
+        let peer = self.peer.as_ref().ok_or_else(|| {
+            anyhow!("send before peer is known (server hasn't received yet)")
+        })?;
+        let len = self.inner.send_to(buf, peer).await?;
+        Ok(len)
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let (len, recv_addr) = self.inner.recv_from(buf).await?;
+        let recv_path = recv_addr
+            .as_pathname()
+            .ok_or_else(|| anyhow!("recv from an unnamed unix socket"))?
+            .to_path_buf();
+        utils::track_peer(&mut self.peer, recv_path)?;
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn round_trips_a_frame() {
+        let path = std::env::temp_dir()
+            .join(format!("niceperf-latency-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut server = UnixLatency::<socket_kind::Server>::new(&path);
+        let mut client = UnixLatency::<socket_kind::Client>::new(&path, 0);
+
+        client.send(b"hello").await.unwrap();
+        let mut buf = [0u8; 1024];
+        let len = server.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"hello");
+
+        server.send(b"world").await.unwrap();
+        let len = client.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"world");
+    }
+}
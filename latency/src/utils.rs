@@ -0,0 +1,17 @@
+use anyhow::{anyhow, Result};
+
+pub fn track_peer<A: PartialEq + Clone>(
+    peer: &mut Option<A>,
+    recv_addr: A,
+) -> Result<()> {
+    match peer {
+        Some(known) if *known != recv_addr => {
+            Err(anyhow!("recv from wrong addr"))
+        }
+        Some(_) => Ok(()),
+        None => {
+            *peer = Some(recv_addr);
+            Ok(())
+        }
+    }
+}